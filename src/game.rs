@@ -1,10 +1,13 @@
 
-use rand::{self, Rng};
+use rand::{self, Rng, SeedableRng, StdRng};
 use std::convert::From;
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::fmt;
 
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
 use info::*;
 
 /*
@@ -13,6 +16,8 @@ use info::*;
 
 pub type Color = &'static str;
 pub const COLORS: [Color; 5] = ["blue", "red", "yellow", "white", "green"];
+// the optional sixth suit, enabled by GameOptions::rainbow
+pub const RAINBOW: Color = "rainbow";
 
 pub type Value = u32;
 // list of (value, count) pairs
@@ -20,7 +25,52 @@ pub const VALUES : [Value; 5] = [1, 2, 3, 4, 5];
 pub const VALUE_COUNTS : [(Value, u32); 5] = [(1, 3), (2, 2), (3, 2), (4, 2), (5, 1)];
 pub const FINAL_VALUE : Value = 5;
 
+// Colors are interned `&'static str`s, which serde cannot deserialize directly
+// (there is no `Deserialize` impl for `&'static str`).  The serde path therefore
+// round-trips colors through owned strings and re-interns them on the way back.
+#[cfg(feature = "serde")]
+fn intern_color(name: &str) -> Color {
+    COLORS.iter().cloned().chain(Some(RAINBOW))
+        .find(|color| *color == name)
+        .unwrap_or_else(|| panic!("unknown color: {}", name))
+}
+
+#[cfg(feature = "serde")]
+mod color_serde {
+    use super::{Color, intern_color};
+    use serde::{Serializer, Deserializer, Deserialize};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(color)
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(intern_color(&name))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod fireworks_serde {
+    use std::collections::HashMap;
+    use super::{Cards, Color, intern_color};
+    use serde::{Serializer, Deserializer, Deserialize};
+
+    pub fn serialize<S: Serializer>(fireworks: &HashMap<Color, Cards>, serializer: S)
+        -> Result<S::Ok, S::Error>
+    {
+        serializer.collect_map(fireworks.iter().map(|(color, cards)| (*color, cards)))
+    }
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<HashMap<Color, Cards>, D::Error>
+    {
+        let raw = HashMap::<String, Cards>::deserialize(deserializer)?;
+        Ok(raw.into_iter().map(|(name, cards)| (intern_color(&name), cards)).collect())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Card {
+    #[cfg_attr(feature = "serde", serde(with = "color_serde"))]
     pub color: Color,
     pub value: Value,
 }
@@ -31,6 +81,7 @@ impl fmt::Debug for Card {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 // basically a stack of cards, or card info
 pub struct Pile<T>(Vec<T>);
 impl <T> Pile<T> {
@@ -52,10 +103,18 @@ impl <T> Pile<T> {
     pub fn shuffle(&mut self) {
         rand::thread_rng().shuffle(&mut self.0[..]);
     }
+    pub fn shuffle_with<R: Rng>(&mut self, rng: &mut R) {
+        rng.shuffle(&mut self.0[..]);
+    }
     pub fn size(&self) -> usize {
         self.0.len()
     }
 }
+impl <T> Default for Pile<T> {
+    fn default() -> Pile<T> {
+        Pile::new()
+    }
+}
 impl <T> From<Vec<T>> for Pile<T> {
     fn from(items: Vec<T>) -> Pile<T> {
         Pile(items)
@@ -68,20 +127,34 @@ pub type CardsInfo = Pile<CardInfo>;
 
 pub type Player = u32;
 
+// the content of a hint: either a color or a value
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Hint {
-    Color,
-    Value,
+    Color(#[cfg_attr(feature = "serde", serde(with = "color_serde"))] Color),
+    Value(Value),
 }
 
 // represents the choice a player made in a given turn
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum TurnChoice {
-    Hint,
+    Hint {
+        player: Player,
+        hinted: Hint,
+    },
     Discard(usize),
     Play(usize),
 }
 
+// an ordered log of the choices applied in a game, paired with the deck seed,
+// sufficient to replay it from the same GameOptions
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameRecord {
+    pub seed: u32,
+    pub turns: Vec<TurnChoice>,
+}
+
 // represents a turn taken in the game
 pub struct Turn<'a> {
     pub player: &'a Player,
@@ -89,6 +162,7 @@ pub struct Turn<'a> {
 }
 
 // represents possible settings for the game
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct GameOptions {
     pub num_players: u32,
     pub hand_size: u32,
@@ -96,23 +170,78 @@ pub struct GameOptions {
     pub num_hints: u32,
     // when hits 0, you lose
     pub num_lives: u32,
+    // include a sixth "rainbow" suit in the deck and fireworks
+    pub rainbow: bool,
+    // whether a rainbow card is touched by every color hint (wildcard variant);
+    // only meaningful when `rainbow` is set
+    pub rainbow_wildcard: bool,
+}
+
+// The number of copies of each value present for a given suit.  The rainbow
+// suit is a singleton suit -- one card per value -- whereas the five standard
+// suits follow the usual 3/2/2/2/1 distribution.
+fn value_counts_for(color: Color) -> Vec<(Value, u32)> {
+    if color == RAINBOW {
+        VALUES.iter().map(|&value| (value, 1)).collect()
+    } else {
+        VALUE_COUNTS.to_vec()
+    }
+}
+
+impl GameOptions {
+    // the suits in play for these options
+    pub fn colors(&self) -> Vec<Color> {
+        let mut colors = COLORS.to_vec();
+        if self.rainbow {
+            colors.push(RAINBOW);
+        }
+        colors
+    }
 }
 
 // The state of a given player:  all other players may see this
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct PlayerState {
     // the player's actual hand
     pub hand: Cards,
-    // represents what is common knowledge about the player's hand
+    // represents what is common knowledge about the player's hand.  `CardInfo`
+    // lives in the (out-of-chunk) `info` module and carries no serde derives,
+    // so this derived state is not serialized; it is rebuilt from the hand on
+    // deserialize (see the `Deserialize` impl below) to keep the
+    // `info.len() == hand.len()` invariant the engine relies on.
+    #[cfg_attr(feature = "serde", serde(skip))]
     pub info: CardsInfo,
 }
 
+// `info` is not serialized, so reconstruct it as one blank `CardInfo` per card
+// in the deserialized hand.  The recorded hints are not replayed here; callers
+// who need the full common-knowledge state should reconstruct it via
+// `GameState::replay`.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for PlayerState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct PlayerStateData {
+            hand: Cards,
+        }
+        let data = PlayerStateData::deserialize(deserializer)?;
+        let infos = (0..data.hand.size()).map(|_| CardInfo::new()).collect::<Vec<_>>();
+        Ok(PlayerState {
+            hand: data.hand,
+            info: CardsInfo::from(infos),
+        })
+    }
+}
+
 // State of everything except the player's hands
 // Is all completely common knowledge
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct BoardState {
     deck: Cards,
     pub discard: Cards,
+    #[cfg_attr(feature = "serde", serde(with = "fireworks_serde"))]
     pub fireworks: HashMap<Color, Cards>,
 
     pub num_players: u32,
@@ -126,6 +255,8 @@ pub struct BoardState {
     pub hints_remaining: u32,
     pub lives_total: u32,
     pub lives_remaining: u32,
+    // whether rainbow cards are touched by every color hint
+    pub rainbow_wildcard: bool,
     // only relevant when deck runs out
     deckless_turns_remaining: u32,
 }
@@ -146,6 +277,7 @@ pub struct GameStateView<'a> {
 
 // complete game state (known to nobody!)
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct GameState {
     pub player_states: HashMap<Player, PlayerState>,
     pub board: BoardState,
@@ -155,8 +287,38 @@ pub type Score = u32;
 
 impl GameState {
     pub fn new(opts: &GameOptions) -> GameState {
-        let mut deck = GameState::make_deck();
+        let mut deck = GameState::make_deck(opts);
+        deck.shuffle();
+        GameState::with_deck(opts, deck)
+    }
+
+    // Like `new`, but shuffles the deck with a seeded RNG so that games -- and
+    // thus replays -- are reproducible.
+    pub fn new_seeded(opts: &GameOptions, seed: u32) -> GameState {
+        let mut rng = StdRng::from_seed(&[seed as usize]);
+        let mut deck = GameState::make_deck(opts);
+        deck.shuffle_with(&mut rng);
+        GameState::with_deck(opts, deck)
+    }
 
+    // Deterministically reconstruct a finished game from its starting options
+    // and the recorded deck seed and ordered choices.
+    pub fn replay(opts: &GameOptions, record: &GameRecord) -> GameState {
+        let mut game = GameState::new_seeded(opts, record.seed);
+        for choice in &record.turns {
+            // stop at the recorded ending so an over-long trace (e.g. a play
+            // after the last life is gone) yields a reconstructed state instead
+            // of panicking on an underflow; other illegal choices still panic
+            if game.is_over() {
+                break;
+            }
+            game.process_choice(choice);
+        }
+        game
+    }
+
+    // Build the initial state from an already-ordered deck.
+    fn with_deck(opts: &GameOptions, mut deck: Cards) -> GameState {
         let mut player_states : HashMap<Player, PlayerState> = HashMap::new();
         for i in 0..opts.num_players {
             let raw_hand = (0..opts.hand_size).map(|_| {
@@ -174,7 +336,7 @@ impl GameState {
         }
 
         let mut fireworks : HashMap<Color, Cards> = HashMap::new();
-        for color in COLORS.iter() {
+        for color in opts.colors() {
             let mut firework = Cards::new();
             let card = Card { value: 0, color: color };
             firework.place(card);
@@ -194,23 +356,23 @@ impl GameState {
                 hints_remaining: opts.num_hints,
                 lives_total: opts.num_lives,
                 lives_remaining: opts.num_lives,
+                rainbow_wildcard: opts.rainbow && opts.rainbow_wildcard,
                 // number of turns to play with deck length ran out
                 deckless_turns_remaining: opts.num_players + 1,
             }
         }
     }
 
-    fn make_deck() -> Cards {
+    fn make_deck(opts: &GameOptions) -> Cards {
         let mut deck: Cards = Cards::from(Vec::new());
 
-        for color in COLORS.iter() {
-            for &(value, count) in VALUE_COUNTS.iter() {
+        for color in opts.colors() {
+            for (value, count) in value_counts_for(color) {
                 for _ in 0..count {
                     deck.place(Card {color: color, value: value});
                 }
             }
         };
-        deck.shuffle();
         info!("Created deck: {:?}", deck);
         deck
     }
@@ -220,9 +382,10 @@ impl GameState {
     }
 
     pub fn is_over(&self) -> bool {
-        // TODO: add condition that fireworks cannot be further completed?
         (self.board.lives_remaining == 0) ||
-        (self.board.deckless_turns_remaining == 0)
+        (self.board.deckless_turns_remaining == 0) ||
+        // the fireworks can no longer be advanced towards a higher score
+        (self.max_score() == self.score())
     }
 
     pub fn score(&self) -> Score {
@@ -234,12 +397,44 @@ impl GameState {
         score as u32
     }
 
+    // The highest score still achievable from here.  For each suit we climb
+    // value-by-value from the current firework top and stop at the first value
+    // whose every copy has been discarded, since that firework can never
+    // progress past that point.
+    pub fn max_score(&self) -> Score {
+        let mut max = 0;
+        for (color, firework) in &self.board.fireworks {
+            // the 0 card we pushed means the top value is the current height
+            let top = firework.top().unwrap().value;
+            let mut reachable = top;
+            for &value in VALUES.iter() {
+                if value <= top {
+                    continue;
+                }
+                let total = value_counts_for(*color).iter()
+                    .find(|&&(v, _)| v == value)
+                    .map(|&(_, count)| count)
+                    .unwrap();
+                let discarded = self.board.discard.0.iter()
+                    .filter(|card| card.color == *color && card.value == value)
+                    .count() as u32;
+                // once every copy of a value is gone the suit stalls there
+                if discarded >= total {
+                    break;
+                }
+                reachable = value;
+            }
+            max += reachable;
+        }
+        max
+    }
+
     // get the game state view of a particular player
     pub fn get_view(&self, player: Player) -> GameStateView {
         let mut other_player_states = HashMap::new();
         for (other_player, state) in &self.player_states {
             if player != *other_player {
-                other_player_states.insert(player, state);
+                other_player_states.insert(*other_player, state);
             }
         }
         GameStateView {
@@ -270,12 +465,52 @@ impl GameState {
 
     pub fn process_choice(&mut self, choice: &TurnChoice) {
         match *choice {
-            TurnChoice::Hint => {
+            TurnChoice::Hint { player, ref hinted } => {
                 assert!(self.board.hints_remaining > 0);
+                assert!(
+                    self.board.player != player,
+                    "Player {} cannot hint themselves", player
+                );
+                let wildcard = self.board.rainbow_wildcard;
+                let ref mut state = self.player_states.get_mut(&player).unwrap();
+
+                // a hint is only legal if it touches at least one card; in the
+                // wildcard variant a rainbow card is touched by any color hint
+                let matches = |card: &Card| {
+                    match *hinted {
+                        Hint::Color(color) => {
+                            card.color == color || (wildcard && card.color == RAINBOW)
+                        }
+                        Hint::Value(value) => card.value == value,
+                    }
+                };
+                assert!(
+                    state.hand.0.iter().any(|card| matches(card)),
+                    "Hint {:?} does not apply to any of player {}'s cards",
+                    hinted, player
+                );
+
+                // record the hint as common knowledge on every card: the cards
+                // it touches learn "is this color/value", the rest learn "is not"
+                for (card, card_info) in
+                    state.hand.0.iter().zip(state.info.0.iter_mut())
+                {
+                    match *hinted {
+                        Hint::Color(color) => {
+                            // A wildcard-touched rainbow card only narrows to
+                            // "this color OR rainbow", so asserting either "is"
+                            // or "is not this color" would wrongly eliminate a
+                            // possibility -- leave its colour knowledge untouched.
+                            if wildcard && card.color == RAINBOW {
+                                continue;
+                            }
+                            card_info.mark_color(color, card.color == color);
+                        }
+                        Hint::Value(value) => card_info.mark_value(value, card.value == value),
+                    }
+                }
+
                 self.board.hints_remaining -= 1;
-                // TODO: actually inform player of values..
-                // nothing to update, really...
-                // TODO: manage common knowledge
             }
             TurnChoice::Discard(index) => {
                 let card = self.take_from_hand(index);
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use game::*;
+
+// A strategy decides, given a player's view of the game, what to do on their
+// turn.  One boxed strategy is kept alive per player for the whole game, so
+// implementations may accumulate state across turns.
+pub trait Strategy {
+    fn decide(&mut self, view: &GameStateView) -> TurnChoice;
+}
+
+// Factory for strategies: an agent is constructed per player at the start of a
+// game, given that player's initial view.
+pub trait StrategyConfig {
+    fn initialize(&self, player: Player, view: &GameStateView) -> Box<Strategy>;
+}
+
+// Drive an already-constructed game to completion with the given strategies,
+// returning the finished state.
+fn play_game(mut game: GameState, strat_config: &StrategyConfig) -> GameState {
+    let mut strategies: HashMap<Player, Box<Strategy>> = HashMap::new();
+    for player in game.get_players() {
+        let strategy = strat_config.initialize(player, &game.get_view(player));
+        strategies.insert(player, strategy);
+    }
+
+    while !game.is_over() {
+        let player = game.board.player;
+        let choice = {
+            let view = game.get_view(player);
+            let strategy = strategies.get_mut(&player).unwrap();
+            strategy.decide(&view)
+        };
+        game.process_choice(&choice);
+    }
+    game
+}
+
+// Run one game and report the final score.
+pub fn simulate(opts: &GameOptions, strat_config: &StrategyConfig) -> Score {
+    play_game(GameState::new(opts), strat_config).score()
+}
+
+// Aggregate statistics over a batch of simulated games.
+pub struct SimulatorResult {
+    pub scores: Vec<Score>,
+    pub lives_lost: Vec<u32>,
+    // the score of a flawless game, which depends on the number of suits in play
+    pub perfect_score: Score,
+}
+impl SimulatorResult {
+    pub fn mean_score(&self) -> f32 {
+        let total: Score = self.scores.iter().sum();
+        (total as f32) / (self.scores.len() as f32)
+    }
+    pub fn median_score(&self) -> Score {
+        let mut sorted = self.scores.clone();
+        sorted.sort();
+        sorted[sorted.len() / 2]
+    }
+    pub fn min_score(&self) -> Score {
+        *self.scores.iter().min().unwrap()
+    }
+    pub fn max_score(&self) -> Score {
+        *self.scores.iter().max().unwrap()
+    }
+    pub fn win_rate(&self) -> f32 {
+        let wins = self.scores.iter().filter(|&&s| s == self.perfect_score).count();
+        (wins as f32) / (self.scores.len() as f32)
+    }
+    pub fn average_lives_lost(&self) -> f32 {
+        let total: u32 = self.lives_lost.iter().sum();
+        (total as f32) / (self.lives_lost.len() as f32)
+    }
+}
+
+// Run `n` games -- one per seed, so benchmarks are reproducible -- and collect
+// statistics so strategy authors can compare agents.  With `n == 0` the result
+// is empty and the summary is skipped; the per-field accessors assume at least
+// one game was run.
+pub fn simulate_n(opts: &GameOptions, strat_config: &StrategyConfig, n: u32) -> SimulatorResult {
+    let mut scores = Vec::new();
+    let mut lives_lost = Vec::new();
+    for seed in 0..n {
+        let game = play_game(GameState::new_seeded(opts, seed), strat_config);
+        scores.push(game.score());
+        lives_lost.push(opts.num_lives - game.board.lives_remaining);
+    }
+    let result = SimulatorResult {
+        scores: scores,
+        lives_lost: lives_lost,
+        perfect_score: (opts.colors().len() as u32) * FINAL_VALUE,
+    };
+    if n > 0 {
+        info!(
+            "Over {} games: mean {}, median {}, min {}, max {}, win rate {}, avg lives lost {}",
+            n, result.mean_score(), result.median_score(), result.min_score(),
+            result.max_score(), result.win_rate(), result.average_lives_lost()
+        );
+    }
+    result
+}